@@ -10,6 +10,7 @@
 use crate::{Coords, RawPoscar, ScaleLine, Poscar};
 
 use std::rc::Rc;
+use std::fmt;
 use std::io::prelude::*;
 use std::ops::Range;
 use std::str::FromStr;
@@ -17,6 +18,28 @@ use std::cmp::Ordering;
 use std::path::{Path, PathBuf};
 
 pub(crate) use self::error::ParseError;
+pub use self::diagnostic::{Diagnostic, Level, DiagnosticKind, LintLevels};
+
+/// A display name for a parser's input, decoupled from any actual filesystem
+/// path the data may or may not have come from (mirrors how rustc's
+/// `SourceFile` separates the name shown in diagnostics from the bytes it
+/// was read from).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SourceName {
+    Path(PathBuf),
+    Virtual(String),
+    Anonymous,
+}
+
+impl fmt::Display for SourceName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceName::Path(p) => write!(f, "{}", p.display()),
+            SourceName::Virtual(s) => write!(f, "{}", s),
+            SourceName::Anonymous => write!(f, "<input>"),
+        }
+    }
+}
 
 impl Poscar {
     /// Reads a POSCAR from an open file or a `&[u8]` buffer.
@@ -33,18 +56,192 @@ impl Poscar {
     /// [`Read::take`]: https://doc.rust-lang.org/std/io/trait.Read.html#method.take
     /// [`BufRead`]: https://doc.rust-lang.org/std/io/trait.BufRead.html
     /// [`BufReader`]: https://doc.rust-lang.org/std/io/struct.BufReader.html
-    // NOTE: This form is unable to include a filename in error messages.
-    // FIXME how do other libraries handle this?
-    //       maybe the filename is simply not this crate's responsibility?
+    ///
+    /// Error messages from this form will show `<input>` in place of a
+    /// filename. Use [`from_reader_named`] if you have a more meaningful
+    /// name to show (e.g. an archive entry), or [`from_path`] if the data
+    /// really does come from the filesystem.
+    ///
+    /// [`from_reader_named`]: #method.from_reader_named
+    /// [`from_path`]: #method.from_path
     pub fn from_reader<R: BufRead>(f: R) -> Result<Self, failure::Error>
-    { _from_reader(f, None::<PathBuf>) }
+    { _from_reader(f, SourceName::Anonymous, &LintLevels::default()).map(|(poscar, _)| poscar) }
 
     /// Reads a POSCAR from the filesystem.
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, failure::Error>
     {
         let f = std::fs::File::open(path.as_ref())?;
         let f = std::io::BufReader::new(f);
-        _from_reader(f, Some(path))
+        _from_reader(f, SourceName::Path(path.as_ref().to_owned()), &LintLevels::default()).map(|(poscar, _)| poscar)
+    }
+
+    /// Like [`from_reader`], but tags error messages with `name` instead of
+    /// the generic `<input>`. Useful when the data isn't on the filesystem
+    /// (an in-memory buffer, an archive entry, a network stream) but still
+    /// has a meaningful display name.
+    pub fn from_reader_named<R: BufRead, S: Into<String>>(f: R, name: S) -> Result<Self, failure::Error>
+    { _from_reader(f, SourceName::Virtual(name.into()), &LintLevels::default()).map(|(poscar, _)| poscar) }
+
+    /// Like [`from_reader`], but also returns the non-fatal [`Diagnostic`]s
+    /// produced for "fishy but technically valid" input (e.g. an indented
+    /// coordinate-system line, or a scale line with VASP's undocumented
+    /// per-axis scaling).
+    ///
+    /// Pass a [`LintLevels`] to promote specific kinds of diagnostics to
+    /// hard errors instead of warnings.
+    pub fn from_reader_with_warnings<R: BufRead>(
+        f: R,
+        lints: &LintLevels,
+    ) -> Result<(Self, Vec<Diagnostic>), failure::Error>
+    { _from_reader(f, SourceName::Anonymous, lints) }
+
+    /// Like [`from_path`], but also returns diagnostics; see
+    /// [`from_reader_with_warnings`].
+    pub fn from_path_with_warnings<P: AsRef<Path>>(
+        path: P,
+        lints: &LintLevels,
+    ) -> Result<(Self, Vec<Diagnostic>), failure::Error>
+    {
+        let f = std::fs::File::open(path.as_ref())?;
+        let f = std::io::BufReader::new(f);
+        _from_reader(f, SourceName::Path(path.as_ref().to_owned()), lints)
+    }
+
+    /// Extracts the source position an error from one of this type's
+    /// `from_*` constructors points at, for callers that want to act on
+    /// *where* a parse failed (e.g. a test asserting the exact line, or an
+    /// editor plugin placing a squiggle) instead of just displaying the
+    /// message.
+    ///
+    /// Returns `None` for errors with no location, such as an I/O error.
+    /// An unexpected end of file reports the line where the input ran out,
+    /// with no column.
+    pub fn error_location(err: &failure::Error) -> Option<ErrorLocation> {
+        let err = err.downcast_ref::<ParseError>()?;
+        Some(ErrorLocation { line: err.line?, col: err.col })
+    }
+
+    /// Renders an error from one of this type's `from_*` constructors as a
+    /// rustc-style caret diagnostic (the offending source line followed by a
+    /// `^` run under the span that failed), for callers that already show
+    /// `from_path`/`from_reader`'s `Display` output and want the annotated
+    /// form explicitly rather than relying on it being the default.
+    ///
+    /// Returns `None` for errors with no parse error to render, such as an
+    /// I/O error.
+    pub fn render_pretty_error(err: &failure::Error) -> Option<String> {
+        Some(err.downcast_ref::<ParseError>()?.render_pretty())
+    }
+}
+
+/// A 0-based source position reported by a parse error, as returned by
+/// [`Poscar::error_location`]. Matches the convention used internally by
+/// error messages (which display 1-based line/column numbers).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorLocation {
+    pub line: usize,
+    pub col: Option<usize>,
+}
+
+mod diagnostic {
+    use super::*;
+    use std::fmt;
+    use std::collections::HashMap;
+
+    /// The severity of a [`Diagnostic`].
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum Level { Warning, Error }
+
+    /// Identifies a specific kind of "fishy but technically valid" situation
+    /// that the parser would otherwise swallow silently. Used as the key for
+    /// [`LintLevels`] overrides.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+    pub enum DiagnosticKind {
+        /// The coordinate-system control line is indented; VASP still reads
+        /// this as `Direct`.
+        IndentedCoordLine,
+        /// The coordinate-system control line's first character is not one
+        /// of the recognized flags (`cCkKdD`); VASP still reads this as `Direct`.
+        SuspiciouslyDirect,
+        /// The scale line has exactly three floats, which matches VASP's
+        /// undocumented (and largely broken) per-axis scaling feature.
+        AmbiguousScaleAxes,
+        /// The symbol/count line had trailing tokens that did not parse as
+        /// atom counts, and were silently treated as a comment.
+        TrailingCountTokensIgnored,
+    }
+
+    impl DiagnosticKind {
+        fn message(self) -> &'static str {
+            match self {
+                DiagnosticKind::IndentedCoordLine =>
+                    "coordinate-system line is indented; treating as Direct",
+                DiagnosticKind::SuspiciouslyDirect =>
+                    "coordinate-system line does not start with a recognized flag; treating as Direct",
+                DiagnosticKind::AmbiguousScaleAxes =>
+                    "scale line has three floats; VASP's undocumented per-axis scaling is ignored here",
+                DiagnosticKind::TrailingCountTokensIgnored =>
+                    "trailing tokens on the symbol/count line were not parsed as atom counts",
+            }
+        }
+    }
+
+    /// A non-fatal diagnostic produced while parsing, for input that is
+    /// technically valid but fishy enough to be worth a second look. See
+    /// [`LintLevels`] to have specific kinds fail the parse instead.
+    #[derive(Debug, Clone)]
+    pub struct Diagnostic {
+        pub level: Level,
+        pub kind: DiagnosticKind,
+        pub line: usize,
+        pub col: Option<usize>,
+    }
+
+    impl fmt::Display for Diagnostic {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}: {}: {}", self.line + 1, match self.level {
+                Level::Warning => "warning",
+                Level::Error => "error",
+            }, self.kind.message())
+        }
+    }
+
+    /// Overrides the default [`Level`] of specific [`DiagnosticKind`]s,
+    /// letting a caller reject "fishy but technically valid" POSCARs that
+    /// the parser otherwise only warns about.
+    #[derive(Debug, Clone, Default)]
+    pub struct LintLevels(HashMap<DiagnosticKind, Level>);
+
+    impl LintLevels {
+        pub fn new() -> Self { LintLevels(HashMap::new()) }
+
+        /// Promote (or demote) a specific kind of diagnostic.
+        pub fn set(&mut self, kind: DiagnosticKind, level: Level) -> &mut Self {
+            self.0.insert(kind, level);
+            self
+        }
+
+        fn level_for(&self, kind: DiagnosticKind) -> Level {
+            self.0.get(&kind).copied().unwrap_or(Level::Warning)
+        }
+    }
+
+    // Records a fishy-but-valid situation, honoring any `LintLevels` override:
+    // pushes a warning `Diagnostic` by default, or fails the parse with a
+    // `ParseError` built from `spanned` if the kind has been promoted.
+    pub(super) fn emit<S: AsRef<str>>(
+        diagnostics: &mut Vec<Diagnostic>,
+        lints: &LintLevels,
+        kind: DiagnosticKind,
+        spanned: &Spanned<S>,
+    ) -> Result<(), ParseError> {
+        match lints.level_for(kind) {
+            Level::Warning => {
+                diagnostics.push(Diagnostic { level: Level::Warning, kind, line: spanned.line, col: Some(spanned.col) });
+                Ok(())
+            },
+            Level::Error => Err(spanned.error(kind.message())),
+        }
     }
 }
 
@@ -56,27 +253,80 @@ mod error {
     #[derive(Debug, Fail)]
     pub(crate) struct ParseError {
         pub(crate) kind: Kind,
-        pub(crate) path: Option<PathBuf>,
+        pub(crate) path: SourceName,
         // (NOTE: these are zero-based for maximum comfort, but the Display
         //        impl will use one-based indices for convention)
         pub(crate) line: Option<usize>,
         pub(crate) col: Option<usize>,
+        // The full text of the offending line, and the length of the span
+        // within it (in Unicode scalar values, matching `col`), kept around
+        // so that `render_pretty` can print a rustc-style caret underneath
+        // the span that failed.  `None` when the error has no specific line
+        // (e.g. "unexpected end of file").
+        pub(crate) line_text: Option<String>,
+        pub(crate) len: Option<usize>,
+        // A suggestion attached when the failing token contains a Unicode
+        // character easily mistaken for an ASCII one (e.g. U+2212 MINUS SIGN
+        // instead of '-'). `None` unless `Spanned::parse` found one.
+        pub(crate) hint: Option<String>,
     }
 
-    impl fmt::Display for ParseError {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self.path.as_ref() {
-                Some(p) => write!(f, "{}:", p.display())?,
-                None => write!(f, "<input>:")?,
-            }
+    impl ParseError {
+        // The plain `path:line:col: message` header, with no source snippet.
+        // Used both as the one-line `Display` fallback and as the first line
+        // of `render_pretty`'s output (kept separate from `render_pretty` to
+        // avoid the latter recursing into `Display` via `self.to_string()`).
+        fn header(&self) -> String {
+            let mut s = format!("{}:", self.path);
 
             match (self.line, self.col) {
                 (None, _) => {}
-                (Some(r), None) => write!(f, "{}: ", r + 1)?,
-                (Some(r), Some(c)) => write!(f, "{}:{}: ", r + 1, c + 1)?,
+                (Some(r), None) => s.push_str(&format!("{}: ", r + 1)),
+                (Some(r), Some(c)) => s.push_str(&format!("{}:{}: ", r + 1, c + 1)),
+            }
+
+            s.push_str(&self.kind.to_string());
+
+            if let Some(hint) = self.hint.as_ref() {
+                s.push_str(&format!("\nhint: {}", hint));
             }
+            s
+        }
+
+        /// Renders this error the way rustc renders a span diagnostic: the
+        /// usual `path:line:col: message` header, followed by the offending
+        /// source line and a caret run underneath the span that failed.
+        ///
+        /// Falls back to the plain header when the error has no line of text
+        /// to show (e.g. an unexpected end of file). This is what `Display`
+        /// uses by default; call it directly if you'd rather have the string
+        /// without going through `failure::Error`/`to_string`.
+        pub(crate) fn render_pretty(&self) -> String {
+            let header = self.header();
+            let (line_text, col) = match (self.line_text.as_ref(), self.col) {
+                (Some(line_text), Some(col)) => (line_text, col),
+                _ => return header,
+            };
+
+            let line_num = self.line.map(|line| line + 1).unwrap_or(0);
+            let gutter = line_num.to_string();
+            let pad: String = gutter.bytes().map(|_| ' ').collect();
+            let len = self.len.unwrap_or(1).max(1);
 
-            <Kind as fmt::Display>::fmt(&self.kind, f)
+            format!(
+                "{header}\n{pad} |\n{gutter} | {line_text}\n{pad} | {marker}{carets}",
+                header=header, pad=pad, gutter=gutter, line_text=line_text,
+                marker=" ".repeat(col), carets="^".repeat(len),
+            )
+        }
+    }
+
+    impl fmt::Display for ParseError {
+        // Defaults to the caret-annotated rendering per `render_pretty`'s doc
+        // comment, so `from_path`/`from_reader` callers get it for free just
+        // by printing the `failure::Error` they got back.
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.render_pretty())
         }
     }
 
@@ -101,7 +351,7 @@ mod error {
 // (NOTE: we could probably replace all this garbage with nom. Any takers?)
 #[derive(Debug, Clone)]
 pub(crate) struct Lines<I> {
-    path: Option<Rc<PathBuf>>,
+    path: Rc<SourceName>,
     cur: usize,
     // (fused to guarantee that scanning for EOF is an idempotent operation)
     lines: std::iter::Fuse<I>,
@@ -110,9 +360,13 @@ pub(crate) struct Lines<I> {
 // string with span info for errors
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct Spanned<S=String> {
-    path: Option<Rc<PathBuf>>,
+    path: Rc<SourceName>,
     line: usize,
     col: usize,
+    // The full, unsliced text of the line this span was taken from, so that
+    // an error built from a sub-slice (e.g. a single word) can still render
+    // the whole line it came from.
+    full_line: Rc<str>,
     s: S,
 }
 
@@ -121,9 +375,9 @@ where
     I: Iterator<Item=Result<String, E>>,
     E: failure::Fail,
 {
-    pub(crate) fn new<P: AsRef<Path>>(lines: I, path: Option<P>) -> Self
+    pub(crate) fn new(lines: I, name: SourceName) -> Self
     { Self {
-        path: path.map(|p| Rc::new(p.as_ref().to_owned())),
+        path: Rc::new(name),
         lines: lines.fuse(),
         cur: 0,
     }}
@@ -136,14 +390,18 @@ where
         let s = self.lines.next().ok_or_else(|| {
             ParseError {
                 kind: "unexpected end of file".into(),
-                path: self.path.as_ref().map(|p| p.as_ref().to_owned()),
+                path: (*self.path).clone(),
                 line: Some(self.cur),
                 col: None,
+                line_text: None,
+                len: None,
+                hint: None,
             }
         })??;
 
         self.cur += 1;
-        Ok(Spanned { path, line, col, s })
+        let full_line = Rc::from(s.as_str());
+        Ok(Spanned { path, line, col, full_line, s })
     }
 
     fn expect_blank_until_eof(&mut self) -> Result<(), failure::Error> {
@@ -156,14 +414,17 @@ where
     }
 }
 
-impl<S> Spanned<S> {
+impl<S: AsRef<str>> Spanned<S> {
     pub(crate) fn error<K>(&self, kind: K) -> ParseError
     where K: Into<error::Kind>,
     { ParseError {
         kind: kind.into(),
-        path: self.path.as_ref().map(|p| p.as_ref().to_owned()),
+        path: (*self.path).clone(),
         line: Some(self.line),
         col: Some(self.col),
+        line_text: Some(self.full_line.to_string()),
+        len: Some(self.s.as_ref().chars().count()),
+        hint: None,
     }}
 }
 
@@ -181,17 +442,23 @@ impl<S: AsRef<str>> Spanned<S> {
     /// Only intended for use by e.g. validation code which wants to test an assumption
     /// about how something will be parsed, using the same logic as the parser itself.
     pub(crate) fn wrap_arbitrary(s: S) -> Self {
-        Spanned { path: None, line: 0, col: 0, s }
+        let full_line = Rc::from(s.as_ref());
+        Spanned { path: Rc::new(SourceName::Anonymous), line: 0, col: 0, full_line, s }
     }
 
     pub(crate) fn as_str(&self) -> &str { self.s.as_ref() }
 
     pub(crate) fn slice(&self, range: Range<usize>) -> Spanned<&str>
     {
+        // `range` is a byte range (required to index into the str), but `col`
+        // is tracked in Unicode scalar values, so it can't simply be added to
+        // `range.start`; count the scalar values that precede the slice instead.
+        let col = self.col + self.s.as_ref()[..range.start].chars().count();
         Spanned {
             path: self.path.clone(),
             line: self.line,
-            col: self.col + range.start,
+            col,
+            full_line: self.full_line.clone(),
             s: &self.s.as_ref()[range],
         }
     }
@@ -228,6 +495,7 @@ impl<S: AsRef<str>> Spanned<S> {
         Words {
             path: self.path.clone(),
             line: self.line,
+            full_line: self.full_line.clone(),
             iter: Box::new(out.into_iter()),
         }
     }
@@ -235,7 +503,13 @@ impl<S: AsRef<str>> Spanned<S> {
     pub(crate) fn parse<T>(&self) -> Result<T, ParseError>
     where T: FromStr,
           T::Err: Into<error::Kind>,
-    { self.s.as_ref().parse().map_err(|e| self.error(e)) }
+    {
+        self.s.as_ref().parse().map_err(|e| {
+            let mut err = self.error(e);
+            err.hint = confusable_hint(self.s.as_ref());
+            err
+        })
+    }
 
 
     // The meaningful character for a flag line. It's the first character, PERIOD.
@@ -245,8 +519,9 @@ impl<S: AsRef<str>> Spanned<S> {
 }
 
 pub(crate) struct Words<'a> {
-    path: Option<Rc<PathBuf>>,
+    path: Rc<SourceName>,
     line: usize,
+    full_line: Rc<str>,
     iter: Box<dyn Iterator<Item=Spanned<&'a str>> + 'a>,
 }
 
@@ -259,22 +534,48 @@ impl<'a> Words<'a> {
     pub(crate) fn next_or_err(&mut self, msg: &str) -> Result<Spanned<&'a str>, ParseError>
     { self.next().ok_or_else(|| ParseError {
         kind: msg.into(),
-        path: self.path.as_ref().map(|p| p.as_ref().to_owned()),
+        path: (*self.path).clone(),
         line: Some(self.line),
         col: None,
+        line_text: Some(self.full_line.to_string()),
+        len: None,
+        hint: None,
     })}
 }
 
+// A tiny table of Unicode characters that are easily mistaken for an ASCII
+// character used in POSCAR numeric/flag fields, in the spirit of rustc's
+// `unicode_chars` confusables lint.
+const CONFUSABLES: &[(char, char)] = &[
+    ('\u{2212}', '-'), // MINUS SIGN
+    ('\u{2013}', '-'), // EN DASH
+    ('\u{2014}', '-'), // EM DASH
+    ('\u{00A0}', ' '), // NO-BREAK SPACE
+    ('\u{FF10}', '0'), ('\u{FF11}', '1'), ('\u{FF12}', '2'), ('\u{FF13}', '3'),
+    ('\u{FF14}', '4'), ('\u{FF15}', '5'), ('\u{FF16}', '6'), ('\u{FF17}', '7'),
+    ('\u{FF18}', '8'), ('\u{FF19}', '9'), // FULLWIDTH DIGITS
+];
+
+fn confusable_hint(s: &str) -> Option<String> {
+    let (bad, good) = s.chars().find_map(|c| {
+        CONFUSABLES.iter().find(|&&(bad, _)| bad == c).copied()
+    })?;
+    Some(format!("found {:?} (U+{:04X}), did you mean {:?}?", bad, bad as u32, good))
+}
+
 #[test]
 fn words() {
+    let path: Rc<SourceName> = Rc::new(SourceName::Anonymous);
+    let full_line: Rc<str> = Rc::from("  aa b   ccc  ");
+
     // test with space at boundaries
-    let s = Spanned { path: None, line: 0, col: 0, s: "  aa b   ccc  " };
+    let s = Spanned { path: path.clone(), line: 0, col: 0, full_line: full_line.clone(), s: "  aa b   ccc  " };
     assert_eq!(
         s.words().collect::<Vec<_>>(),
         vec![
-            Spanned { path: None, line: 0, col: 2, s: "aa" },
-            Spanned { path: None, line: 0, col: 5, s: "b" },
-            Spanned { path: None, line: 0, col: 9, s: "ccc" },
+            Spanned { path: path.clone(), line: 0, col: 2, full_line: full_line.clone(), s: "aa" },
+            Spanned { path: path.clone(), line: 0, col: 5, full_line: full_line.clone(), s: "b" },
+            Spanned { path: path.clone(), line: 0, col: 9, full_line: full_line.clone(), s: "ccc" },
         ],
     );
 
@@ -283,9 +584,9 @@ fn words() {
     assert_eq!(
         s.words().collect::<Vec<_>>(),
         vec![
-            Spanned { path: None, line: 0, col: 3, s: "a" },
-            Spanned { path: None, line: 0, col: 5, s: "b" },
-            Spanned { path: None, line: 0, col: 9, s: "cc" },
+            Spanned { path: path.clone(), line: 0, col: 3, full_line: full_line.clone(), s: "a" },
+            Spanned { path: path.clone(), line: 0, col: 5, full_line: full_line.clone(), s: "b" },
+            Spanned { path: path.clone(), line: 0, col: 9, full_line: full_line.clone(), s: "cc" },
         ],
     );
 }
@@ -399,10 +700,11 @@ fn classify_coord_line(mut line: &str) -> CoordLineType {
     }
 }
 
-fn _from_reader<R, P>(f: R, path: Option<P>) -> Result<Poscar, failure::Error>
-where R: BufRead, P: AsRef<Path>,
+fn _from_reader<R>(f: R, name: SourceName, lints: &LintLevels) -> Result<(Poscar, Vec<Diagnostic>), failure::Error>
+where R: BufRead,
 {
-    let mut lines = Lines::new(f.lines(), path);
+    let mut diagnostics = vec![];
+    let mut lines = Lines::new(f.lines(), name);
 
     let comment = lines.next()?.as_str().to_string();
 
@@ -436,11 +738,15 @@ where R: BufRead, P: AsRef<Path>,
         // puts three floats in that location. This pretty much always generates an error
         // *somewhere*, but sometimes it can be far away from this line.
         //
-        // For these reasons, we'll generate an error when there are two or more floats.
-        if let Some(word) = words.next() {
-            if let Ok(_) = word.parse::<f64>() {
-                bail!(word.error("too many floats on scale line (expected just one)"));
-            }
+        // For these reasons, we generate an error when there are two or more floats...
+        // except in the ambiguous 3-float case, which only gets a warning, since it is
+        // the one combination that VASP itself will (sort of) accept.
+        let rest: Vec<_> = words.collect();
+        let n_floats = rest.iter().take_while(|w| w.parse::<f64>().is_ok()).count();
+        if n_floats == 2 {
+            diagnostic::emit(&mut diagnostics, lints, DiagnosticKind::AmbiguousScaleAxes, &line)?;
+        } else if n_floats > 0 {
+            bail!(rest[0].error("too many floats on scale line (expected just one)"));
         }
     };
 
@@ -477,12 +783,17 @@ where R: BufRead, P: AsRef<Path>,
             },
         };
 
-        let group_counts: Result<Vec<usize>, _> = {
-            counts_line.words().map(|s| parse_unsigned(s.as_str()).map(|x| x as usize))
-                               .take_while(|e| e.is_ok())
-                               .collect()
-        };
-        let group_counts = group_counts?;
+        let count_words: Vec<_> = counts_line.words().collect();
+        let n_parsed = count_words.iter()
+            .map(|s| parse_unsigned(s.as_str()))
+            .take_while(|e| e.is_ok())
+            .count();
+        let group_counts: Vec<usize> = count_words[..n_parsed].iter()
+            .map(|s| parse_unsigned(s.as_str()).unwrap() as usize)
+            .collect();
+        if n_parsed < count_words.len() {
+            diagnostic::emit(&mut diagnostics, lints, DiagnosticKind::TrailingCountTokensIgnored, &counts_line)?;
+        }
 
         if let Some(ref group_symbols) = group_symbols {
             if group_symbols.len() != group_counts.len() {
@@ -512,12 +823,16 @@ where R: BufRead, P: AsRef<Path>,
 
             has_direct = match classify_coord_line(line.as_str()) {
                 CoordLineType::Cartesian => false,
-                // FIXME: Some of these (especially IndendedText) should log warnings
-                //        via the log crate
                 CoordLineType::Direct |
-                CoordLineType::SuspiciouslyDirect |
-                CoordLineType::EmptyOrWhitespace |
-                CoordLineType::IndentedText => true,
+                CoordLineType::EmptyOrWhitespace => true,
+                CoordLineType::SuspiciouslyDirect => {
+                    diagnostic::emit(&mut diagnostics, lints, DiagnosticKind::SuspiciouslyDirect, &line)?;
+                    true
+                },
+                CoordLineType::IndentedText => {
+                    diagnostic::emit(&mut diagnostics, lints, DiagnosticKind::IndentedCoordLine, &line)?;
+                    true
+                },
             };
             // rest is freeform comment
         };
@@ -583,11 +898,17 @@ where R: BufRead, P: AsRef<Path>,
         let (has_direct, status) = match classify_coord_line(line.as_str()) {
             CoordLineType::Cartesian => (false, PresenceIs::Required),
 
-            // FIXME: Some of these (especially IndendedText) should log warnings
-            //        via the log crate
-            CoordLineType::Direct |
-            CoordLineType::SuspiciouslyDirect |
-            CoordLineType::IndentedText => (true, PresenceIs::Required),
+            CoordLineType::Direct => (true, PresenceIs::Required),
+
+            CoordLineType::SuspiciouslyDirect => {
+                diagnostic::emit(&mut diagnostics, lints, DiagnosticKind::SuspiciouslyDirect, &line)?;
+                (true, PresenceIs::Required)
+            },
+
+            CoordLineType::IndentedText => {
+                diagnostic::emit(&mut diagnostics, lints, DiagnosticKind::IndentedCoordLine, &line)?;
+                (true, PresenceIs::Required)
+            },
 
             // If the line is empty, we can't quite be sure yet whether
             // it's a blank line that implies Direct, or if it is just
@@ -664,9 +985,11 @@ where R: BufRead, P: AsRef<Path>,
     //   are only allowed to be present if velocities are present.
     lines.expect_blank_until_eof()?;
 
-    Ok(RawPoscar {
+    let poscar = RawPoscar {
         comment, scale, positions, lattice_vectors,
         group_symbols, group_counts, velocities, dynamics,
         _cant_touch_this: (),
-    }.validate().expect("an invariant was not checked during parsing (this is a bug!)"))
+    }.validate().expect("an invariant was not checked during parsing (this is a bug!)");
+
+    Ok((poscar, diagnostics))
 }
@@ -3,106 +3,396 @@ use ::std::fmt;
 use ::std::io::prelude::*;
 use ::{Poscar, RawPoscar, ScaleLine, Coords};
 
-/// Writes a POSCAR to an io::Write instance.
+/// Writes a POSCAR to an io::Write instance, using the default formatting.
+///
+/// This is equivalent to `WriterBuilder::new().write(w, poscar)`.  See
+/// [`WriterBuilder`] if you need control over float precision, column
+/// widths, or other formatting details.
 ///
 /// **This method does not panic.**  All conditions required for the
 /// successful creation of an output file are already enforced as
 /// invariants of the Poscar datatype.
 pub fn to_writer<W>(
-    mut w: W,
+    w: W,
     poscar: &Poscar,
 ) -> io::Result<()>
 where W: Write
-{
-    let w = &mut w;
-    let &Poscar(RawPoscar {
-        scale, ref lattice_vectors, ref velocities, ref dynamics,
-        ref comment, ref coords, ref group_counts, ref group_symbols,
-    }) = poscar;
+{ WriterBuilder::new().write(w, poscar) }
 
-    assert!(!comment.contains("\n"), "BUG");
-    assert!(!comment.contains("\r"), "BUG");
+/// Configures the text layout used to write a POSCAR.
+///
+/// Following the builder style of `ucd-generate`'s `WriterBuilder`, set
+/// whichever options you care about and then call [`write`][Self::write]:
+///
+/// ```no_run
+/// # use ::poscar::{Poscar, WriterBuilder};
+/// # let poscar: Poscar = unimplemented!();
+/// let mut buf = vec![];
+/// WriterBuilder::new()
+///     .precision(8)
+///     .column_width(14)
+///     .write(&mut buf, &poscar)
+///     .unwrap();
+/// ```
+///
+/// `WriterBuilder::new()` reproduces the exact formatting that [`to_writer`]
+/// has always produced (shortest round-trip floats, a two-space indent with
+/// four spaces before the lattice vectors, and the VASP-5 symbols line when
+/// present).
+#[derive(Debug, Clone)]
+pub struct WriterBuilder {
+    precision: Option<usize>,
+    column_width: Option<usize>,
+    indent: usize,
+    symbols: bool,
+    coord_system: Option<CoordSystem>,
+}
+
+/// The coordinate system that [`WriterBuilder::coord_system`] can request on output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoordSystem {
+    /// Absolute coordinates, in the same length units as the lattice vectors.
+    Cartesian,
+    /// Coordinates expressed as fractions of the lattice vectors.
+    Direct,
+}
 
-    writeln!(w, "{}", comment)?;
-    match scale {
-        ScaleLine::Factor(x) => writeln!(w, "  {}", Dtoa(x))?,
-        ScaleLine::Volume(x) => writeln!(w, "  -{}", Dtoa(x))?,
+impl Default for WriterBuilder {
+    fn default() -> Self { Self::new() }
+}
+
+impl WriterBuilder {
+    /// Create a builder with the same defaults that [`to_writer`] has always used.
+    pub fn new() -> Self {
+        WriterBuilder {
+            precision: None,
+            column_width: None,
+            indent: 2,
+            symbols: true,
+            coord_system: None,
+        }
     }
 
-    for row in lattice_vectors {
-        writeln!(w, "    {}", By3(*row, Dtoa))?;
+    /// Set the number of digits written after the decimal point for lattice
+    /// vectors, coordinates, and velocities.
+    ///
+    /// The default (`None`) uses the shortest string that round-trips back
+    /// to the original `f64`, which is what [`to_writer`] has always done.
+    /// If you need output that is both column-aligned *and* lossless, leave
+    /// this as `None`; there is no fixed precision that is guaranteed to
+    /// round-trip every `f64`, so an explicit `precision` is necessarily an
+    /// approximation (like VASP's own `%.16f`-ish output).
+    pub fn precision(&mut self, precision: usize) -> &mut Self {
+        self.precision = Some(precision);
+        self
     }
 
-    if let Some(group_symbols) = group_symbols.as_ref() {
-        write!(w, "  ")?;
-        write_sep(&mut *w, " ", group_symbols.iter().map(|s| format!("{:>2}", s)))?;
-        writeln!(w)?;
+    /// Right-align each formatted number in a field of at least this many
+    /// characters, so that the coordinate and lattice-vector columns line up.
+    ///
+    /// The default (`None`) writes each number with no padding, exactly as
+    /// [`to_writer`] has always done.
+    pub fn column_width(&mut self, width: usize) -> &mut Self {
+        self.column_width = Some(width);
+        self
     }
 
-    assert!(!group_counts.is_empty(), "BUG");
-    write!(w, "  ")?;
-    write_sep(&mut *w, " ", group_counts.iter().map(|&c| format!("{:>2}", c)))?;
-    writeln!(w)?;
+    /// Set the number of spaces used to indent the coordinate/velocity
+    /// blocks.  Defaults to `2`.  The lattice vectors are indented two
+    /// spaces further than this.
+    ///
+    /// (the scale line and the symbol/count lines always reserve their own
+    /// two-column indent, independently of this setting)
+    pub fn indent(&mut self, indent: usize) -> &mut Self {
+        self.indent = indent;
+        self
+    }
 
-    if let &Some(_) = dynamics {
-        writeln!(w, "Selective Dynamics")?;
+    /// Control whether the VASP-5 elemental symbols line is emitted when
+    /// `group_symbols` is present.  Defaults to `true`.
+    pub fn symbols(&mut self, symbols: bool) -> &mut Self {
+        self.symbols = symbols;
+        self
     }
 
-    match coords {
-        &Coords::Cart(_) => writeln!(w, "Cartesian")?,
-        &Coords::Frac(_) => writeln!(w, "Direct")?,
+    /// Convert coordinates (and velocities, if present) to the requested
+    /// system on output, regardless of how they are stored in the `Poscar`.
+    ///
+    /// The default (`None`) writes coordinates exactly as stored, only
+    /// changing the header word, which is what [`to_writer`] has always done.
+    pub fn coord_system(&mut self, system: CoordSystem) -> &mut Self {
+        self.coord_system = Some(system);
+        self
     }
 
-    let coords = coords.as_ref().raw();
-    for (i, c) in coords.iter().enumerate() {
-        write!(w, "  {}", By3(*c, Dtoa))?;
-        if let &Some(ref dynamics) = dynamics {
-            let fmt = |b| match b { true => 'T', false => 'F' };
-            write!(w, " {}", By3(dynamics[i], fmt))?;
+    /// Write a POSCAR to an `io::Write` instance using the options configured
+    /// on this builder.
+    ///
+    /// **This method does not panic.**  All conditions required for the
+    /// successful creation of an output file are already enforced as
+    /// invariants of the Poscar datatype.
+    pub fn write<W>(
+        &self,
+        w: W,
+        poscar: &Poscar,
+    ) -> io::Result<()>
+    where W: Write
+    { self.render(&mut IoSink(w), poscar) }
+
+    // Shared by `write` (over `io::Write`) and the `Display` impl (over
+    // `fmt::Write`), so the layout logic only has to be written once.
+    fn render<S: Sink>(
+        &self,
+        w: &mut S,
+        poscar: &Poscar,
+    ) -> Result<(), S::Error>
+    {
+        let &Poscar(RawPoscar {
+            scale, ref lattice_vectors, ref velocities, ref dynamics,
+            ref comment, ref coords, ref group_counts, ref group_symbols,
+        }) = poscar;
+
+        assert!(!comment.contains("\n"), "BUG");
+        assert!(!comment.contains("\r"), "BUG");
+
+        let indent = " ".repeat(self.indent);
+        let lattice_indent = " ".repeat(self.indent + 2);
+        let num = |x: f64| self.format_number(x);
+
+        writeln!(w, "{}", comment)?;
+        // (the scale is not a column, so it gets no `column_width` padding)
+        match scale {
+            ScaleLine::Factor(x) => writeln!(w, "  {}", self.format_value(x))?,
+            ScaleLine::Volume(x) => writeln!(w, "  -{}", self.format_value(x))?,
+        }
+
+        for row in lattice_vectors {
+            writeln!(w, "{}{}", lattice_indent, By3(*row, num))?;
+        }
+
+        if self.symbols {
+            if let Some(group_symbols) = group_symbols.as_ref() {
+                write!(w, "  ")?;
+                write_sep(&mut *w, " ", group_symbols.iter().map(|s| format!("{:>2}", s)))?;
+                writeln!(w)?;
+            }
         }
+
+        assert!(!group_counts.is_empty(), "BUG");
+        write!(w, "  ")?;
+        write_sep(&mut *w, " ", group_counts.iter().map(|&c| format!("{:>2}", c)))?;
         writeln!(w)?;
+
+        if let &Some(_) = dynamics {
+            writeln!(w, "Selective Dynamics")?;
+        }
+
+        let coord_system = self.coord_system.unwrap_or_else(|| match coords {
+            &Coords::Cart(_) => CoordSystem::Cartesian,
+            &Coords::Frac(_) => CoordSystem::Direct,
+        });
+        match coord_system {
+            CoordSystem::Cartesian => writeln!(w, "Cartesian")?,
+            CoordSystem::Direct => writeln!(w, "Direct")?,
+        }
+
+        let coords = convert_coords(coords, coord_system, lattice_vectors);
+        for (i, c) in coords.iter().enumerate() {
+            write!(w, "{}{}", indent, By3(*c, num))?;
+            if let &Some(ref dynamics) = dynamics {
+                let fmt = |b| match b { true => 'T', false => 'F' };
+                write!(w, " {}", By3(dynamics[i], fmt))?;
+            }
+            writeln!(w)?;
+        }
+
+        if let &Some(ref velocities) = velocities {
+            let velocity_system = self.coord_system.unwrap_or_else(|| match velocities {
+                &Coords::Cart(_) => CoordSystem::Cartesian,
+                &Coords::Frac(_) => CoordSystem::Direct,
+            });
+            match velocity_system {
+                CoordSystem::Cartesian => writeln!(w, "Cartesian")?,
+                // (NOTE: typical appearance in CONTCAR; pymatgen expects this form)
+                CoordSystem::Direct => writeln!(w, "")?,
+            }
+
+            let velocities = convert_coords(velocities, velocity_system, lattice_vectors);
+            for v in &velocities {
+                writeln!(w, "{}{}", indent, By3(*v, num))?;
+            }
+        }
+
+        Ok(())
     }
 
-    if let &Some(ref velocities) = velocities {
-        match velocities {
-            &Coords::Cart(_) => writeln!(w, "Cartesian")?,
-            // (NOTE: typical appearance in CONTCAR; pymatgen expects this form)
-            &Coords::Frac(_) => writeln!(w, "")?,
+    // Formats a single number according to `precision` alone, with no padding.
+    fn format_value(&self, x: f64) -> String {
+        match self.precision {
+            Some(precision) => format!("{}", FixedFloat { value: x, precision }),
+            None => format!("{}", Dtoa(x)),
         }
+    }
 
-        let velocities = velocities.as_ref().raw();
-        for v in velocities {
-            writeln!(w, "  {}", By3(*v, Dtoa))?;
+    // Formats a single number according to `precision` and `column_width`.
+    fn format_number(&self, x: f64) -> String {
+        let s = self.format_value(x);
+        match self.column_width {
+            Some(width) => format!("{:>width$}", s, width = width),
+            None => s,
         }
     }
+}
 
-    Ok(())
+// Formats `value` to a fixed number of digits after the decimal point, the way
+// VASP's own `%.Nf`-style output does, so that once right-aligned to a common
+// `column_width`, the decimal points in a column line up.  (On its own this
+// does not pad: tokens of different sign or magnitude still differ in width.)
+//
+// Plain `format!("{:.*}", precision, value)` gets 99% of the way there, but
+// rounds small negative magnitudes (e.g. `-1e-20` at precision 2) to `-0.00`;
+// collapse that case to `0.00` so a sign column never lies about the sign of
+// the underlying value.
+struct FixedFloat { value: f64, precision: usize }
+impl fmt::Display for FixedFloat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = format!("{:.*}", self.precision, self.value);
+        let unsigned = if s.starts_with('-') { &s[1..] } else { &s[..] };
+        match unsigned.bytes().all(|b| b == b'0' || b == b'.') {
+            true => f.write_str(unsigned),
+            false => f.write_str(&s),
+        }
+    }
 }
 
-fn write_sep<W, Xs>(mut w: W, sep: &str, xs: Xs) -> io::Result<()>
+// Converts a set of coordinates (positions or velocities) to `target`.
+// If the coordinates are already in the requested system, they are merely
+// cloned. Otherwise, `lattice_vectors` is used as-is, with no `scale` factor
+// applied: every value this crate stores or writes (lattice vectors *and*
+// coordinates alike) is the raw, pre-scale figure, exactly as `parse.rs`
+// leaves it, so `scale` cancels out of the conversion entirely — a reader
+// applies it once, uniformly, to both after parsing.
+fn convert_coords(
+    coords: &Coords<Vec<[f64; 3]>>,
+    target: CoordSystem,
+    lattice_vectors: &[[f64; 3]; 3],
+) -> Vec<[f64; 3]> {
+    match (coords, target) {
+        (&Coords::Cart(ref xs), CoordSystem::Cartesian) |
+        (&Coords::Frac(ref xs), CoordSystem::Direct) => xs.clone(),
+
+        (&Coords::Frac(ref xs), CoordSystem::Cartesian) => {
+            xs.iter().map(|&frac| mat3_row_mul(frac, lattice_vectors)).collect()
+        },
+
+        (&Coords::Cart(ref xs), CoordSystem::Direct) => {
+            let inverse = mat3_inverse(lattice_vectors);
+            xs.iter().map(|&cart| mat3_row_mul(cart, &inverse)).collect()
+        },
+    }
+}
+
+// Multiplies row vector `v` by `matrix`, using the convention that each row
+// of `matrix` is a basis vector: `out[j] = sum_k v[k] * matrix[k][j]`.
+fn mat3_row_mul(v: [f64; 3], matrix: &[[f64; 3]; 3]) -> [f64; 3] {
+    let mut out = [0.0; 3];
+    for j in 0..3 {
+        out[j] = (0..3).map(|k| v[k] * matrix[k][j]).sum::<f64>();
+    }
+    out
+}
+
+fn mat3_inverse(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let [[a, b, c], [d, e, f], [g, h, i]] = *m;
+    let det = a * (e * i - f * h) - b * (d * i - f * g) + c * (d * h - e * g);
+    let inv_det = 1.0 / det;
+    [
+        [(e * i - f * h) * inv_det, (c * h - b * i) * inv_det, (b * f - c * e) * inv_det],
+        [(f * g - d * i) * inv_det, (a * i - c * g) * inv_det, (c * d - a * f) * inv_det],
+        [(d * h - e * g) * inv_det, (b * g - a * h) * inv_det, (a * e - b * d) * inv_det],
+    ]
+}
+
+fn write_sep<S, Xs>(w: &mut S, sep: &str, xs: Xs) -> Result<(), S::Error>
 where
-    W: io::Write,
+    S: Sink,
     Xs: IntoIterator,
     Xs::Item: fmt::Display,
 {
     let mut xs = xs.into_iter();
     if let Some(x) = xs.next() {
-        write!(&mut w, "{}", x)?;
+        write!(w, "{}", x)?;
     }
     for x in xs {
-        write!(&mut w, "{}{}", sep, x)?;
+        write!(w, "{}{}", sep, x)?;
     }
     Ok(())
 }
 
+// Lets the POSCAR layout logic in `WriterBuilder::render` be written once and
+// driven by either an `io::Write` (for `WriterBuilder::write`) or a
+// `fmt::Write` (for the `Display` impl below), whose `write_fmt` methods
+// otherwise have incompatible signatures (`io::Result<()>` vs `fmt::Result`).
+trait Sink {
+    type Error;
+    fn write_fmt(&mut self, args: fmt::Arguments) -> Result<(), Self::Error>;
+}
+
+struct IoSink<W>(W);
+impl<W: Write> Sink for IoSink<W> {
+    type Error = io::Error;
+    fn write_fmt(&mut self, args: fmt::Arguments) -> io::Result<()> {
+        io::Write::write_fmt(&mut self.0, args)
+    }
+}
+
+struct FmtSink<'a, 'b: 'a>(&'a mut fmt::Formatter<'b>);
+impl<'a, 'b> Sink for FmtSink<'a, 'b> {
+    type Error = fmt::Error;
+    fn write_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        fmt::Write::write_fmt(self.0, args)
+    }
+}
+
+/// Renders the POSCAR using [`WriterBuilder`]'s default formatting, so
+/// `format!("{}", poscar)` and `poscar.to_string()` work directly.
+///
+/// Honors the same layout [`to_writer`] has always used; to customize the
+/// output (precision, column widths, coordinate system, ...) build the text
+/// with a [`WriterBuilder`] instead.
+impl fmt::Display for Poscar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        WriterBuilder::new().render(&mut FmtSink(f), self)
+    }
+}
+
 struct Dtoa(f64);
 impl fmt::Display for Dtoa {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // not the most efficient thing in the world...
-        let mut bytes = vec![];
-        ::dtoa::write(&mut bytes, self.0).map_err(|_| fmt::Error)?;
-        f.write_str(&String::from_utf8(bytes).unwrap())
+        // dtoa's shortest round-trip output for an f64 never exceeds a couple
+        // dozen bytes (e.g. "-2.2250738585072014e-308"); format into a stack
+        // buffer instead of allocating one `Vec<u8>` per float.
+        let mut buf = [0u8; 32];
+        let mut cursor = StackWriter { buf: &mut buf, len: 0 };
+        ::dtoa::write(&mut cursor, self.0).map_err(|_| fmt::Error)?;
+        let bytes = &cursor.buf[..cursor.len];
+
+        // dtoa only ever emits ASCII, so re-validating it as UTF-8 (the way
+        // `String::from_utf8` used to) is pure overhead.
+        f.write_str(unsafe { ::std::str::from_utf8_unchecked(bytes) })
+    }
+}
+
+// A fixed-capacity `io::Write` sink backed by a caller-provided stack buffer.
+struct StackWriter<'a> { buf: &'a mut [u8], len: usize }
+impl<'a> io::Write for StackWriter<'a> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let end = self.len + data.len();
+        self.buf[self.len..end].copy_from_slice(data);
+        self.len = end;
+        Ok(data.len())
     }
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
 }
 
 // Formats three space-separated tokens after applying a conversion function to each.
@@ -4,64 +4,138 @@ extern crate poscar;
 #[macro_use]
 extern crate serde;
 extern crate serde_yaml;
-extern crate left_pad;
+extern crate libtest_mimic;
 
+use ::std::fmt;
 use ::std::fs;
-use ::std::path::Path;
+use ::std::sync::{Arc, Mutex};
+use ::std::sync::atomic::{AtomicBool, Ordering};
+use ::std::path::{Path, PathBuf};
 
 use ::poscar::failure::Error as FailError;
 use ::poscar::failure::ResultExt as FailResultExt;
+use ::poscar::{WriterBuilder, CoordSystem, Level, DiagnosticKind, LintLevels};
+
+use ::libtest_mimic::{Arguments, Failed, Trial};
 
 fn main() {
-    let tests = collect_tests("tests/parse".as_ref()).unwrap();
+    // BLESS=1 rewrites each failing fixture's `output`/`error` field with
+    // the freshly produced text instead of failing the test, so that
+    // intentional output-format changes can be accepted across the whole
+    // suite with one command and then reviewed via `git diff`.
+    let bless = ::std::env::var_os("BLESS").is_some();
 
-    println!("running {} tests", tests.len());
+    let args = Arguments::from_args();
+    let specs: Vec<Arc<TestSpec>> = collect_tests("tests/parse".as_ref())
+        .unwrap()
+        .into_iter()
+        .map(Arc::new)
+        .collect();
 
-    let name_pad = tests.iter().map(|test| test.basename.len()).max().unwrap().min(32);
+    let mut trials = vec![];
+    for spec in &specs {
+        for (case_i, case) in spec.cases.iter().enumerate() {
+            // the same Rusty-looking path used in the old dot-printing main,
+            // now doubling as the name `cargo test`'s filtering matches against
+            let meth = case.name.clone().unwrap_or_else(|| format!("case_{}", case_i));
+            let name = format!("{}::{}", spec.basename, meth).replace("-", "_");
 
-    let mut failures = vec![];
-    for test in tests {
+            let spec = spec.clone();
+            trials.push(Trial::test(name, move || -> Result<(), Failed> {
+                match spec.cases[case_i].run() {
+                    Ok(()) => Ok(()),
+                    Err(e) => {
+                        if bless {
+                            if let Some(bonafide) = e.bonafide() {
+                                match spec.kind {
+                                    FixtureKind::Yaml { ref raw, ref blessed } => {
+                                        let at = e.actual_location();
+                                        apply_bless(&mut raw.lock().unwrap()[case_i], bonafide, at);
+                                        blessed.store(true, Ordering::Relaxed);
+                                    },
+                                    // a paired `.poscar` fixture has exactly one
+                                    // case, so there's nothing to batch: just
+                                    // overwrite its target file directly.
+                                    FixtureKind::External { ref target_path } => {
+                                        // Restore the trailing newline `strip_final_newline`
+                                        // removed from a `.err` file on read, so the file stays a
+                                        // well-formed text file. (Writer output for an `.expected`
+                                        // file already ends in a newline.)
+                                        let text = match spec.cases[case_i].kind {
+                                            TestKind::Success(_) => bonafide,
+                                            TestKind::Failure(..) => bonafide + "\n",
+                                        };
+                                        fs::write(target_path, text).unwrap_or_else(|e| {
+                                            panic!("error writing {}: {}", target_path.display(), e);
+                                        });
+                                    },
+                                }
+                                return Ok(());
+                            }
+                        }
+                        Err(Failed::from(format!("{}", e)))
+                    },
+                }
+            }));
+        }
+    }
 
-        print!("  {}.yaml: ", ::left_pad::leftpad(&test.basename[..], name_pad));
-        for (i, case) in test.cases.iter().enumerate() {
-            match case.run() {
-                Ok(()) => print!("."),
-                Err(e) => {
-                    print!("E");
+    let conclusion = libtest_mimic::run(&args, trials);
 
-                    // give the test a Rusty-looking path, just for display purposes
-                    let meth = case.name.clone().unwrap_or_else(|| format!("case_{}", i));
-                    let path = format!("{}::{}", test.basename, meth).replace("-", "_");
-                    failures.push(Failure(path, e));
-                },
+    for spec in &specs {
+        if let FixtureKind::Yaml { ref raw, ref blessed } = spec.kind {
+            if blessed.load(Ordering::Relaxed) {
+                let file = fs::File::create(&spec.path).unwrap();
+                ::serde_yaml::to_writer(file, &*raw.lock().unwrap()).unwrap_or_else(|e| {
+                    panic!("error writing {}: {}", spec.path.display(), e);
+                });
             }
         }
-        println!();
     }
 
-    for failure in &failures {
-        println!();
-        println!(" ------ test {} FAILED! ------", failure.0);
-        println!("Err: {:#?}", failure.1);
-    }
-
-    assert_eq!(failures.len(), 0, "a test has failed!");
+    conclusion.exit();
 }
 
 struct TestSpec {
     basename: String,
+    path: PathBuf,
+    kind: FixtureKind,
     cases: Vec<Test>,
 }
 
+// How a `TestSpec`'s bonafide output gets written back under BLESS, which
+// differs between the two fixture formats this suite supports.
+enum FixtureKind {
+    // A `*.yaml` fixture: the source for every case in the spec, BLESS-ed by
+    // overwriting the whole file once with the (mutex-guarded, so trials can
+    // run concurrently) up-to-date `RawTest` vector.
+    Yaml { raw: Mutex<Vec<RawTest>>, blessed: AtomicBool },
+    // A paired `<name>.poscar` + `<name>.expected`/`<name>.err` fixture: the
+    // spec has exactly one case, BLESS-ed by overwriting the target file.
+    External { target_path: PathBuf },
+}
+
 // Format of test in yaml
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 #[serde(untagged)]
 enum RawTest {
-    Success { name: Option<String>, input: Input, output: String },
-    Failure { name: Option<String>, input: Input, error: String },
+    Success { name: Option<String>, input: Input, output: Input },
+    Failure {
+        name: Option<String>,
+        input: Input,
+        error: Input,
+        // Optional expected error site, 1-based to match the numbers the
+        // error's own `Display` shows. When present, `Test::run` additionally
+        // asserts that the parser reported this exact line (and column, if
+        // given), not just that its message contains `error`.
+        #[serde(default)]
+        line: Option<usize>,
+        #[serde(default)]
+        col: Option<usize>,
+    },
 }
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 #[serde(untagged)]
 enum Input {
     Blob(String), // usually one big "|"-style YAML string
@@ -74,11 +148,23 @@ impl Input {
         Input::Blob(s) => s,
         Input::Lines(lines) => lines.join("\n"),
     }}
+
+    // Rewraps a freshly-produced string in the same Blob-vs-Lines shape as
+    // `self`, so a BLESS rewrite doesn't disturb a fixture's formatting.
+    fn reshape_like(&self, s: String) -> Input
+    { match self {
+        Input::Blob(_) => Input::Blob(s),
+        Input::Lines(_) => Input::Lines(s.split('\n').map(String::from).collect()),
+    }}
 }
 
+// A fixture-authored error site, 1-based to match what `Display` shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ExpectedLocation { line: usize, col: Option<usize> }
+
 // Nicer representation of Test
-struct Test { name: Option<String>, input: String, kind: TestKind }
-enum TestKind { Success(String), Failure(String) }
+struct Test { name: Option<String>, input: String, kind: TestKind, config: FixtureConfig }
+enum TestKind { Success(String), Failure(String, Option<ExpectedLocation>) }
 
 impl RawTest {
     fn unraw(self) -> Test
@@ -88,13 +174,138 @@ impl RawTest {
             RawTest::Failure { name, input, .. } => (name, input.into_string()),
         };
         let kind = match self {
-            RawTest::Success { output, .. } => TestKind::Success(output),
-            RawTest::Failure { error, .. } => TestKind::Failure(error),
+            RawTest::Success { output, .. } => TestKind::Success(output.into_string()),
+            RawTest::Failure { error, line, col, .. } => {
+                let at = line.map(|line| ExpectedLocation { line, col });
+                TestKind::Failure(error.into_string(), at)
+            },
         };
-        Test { name, input, kind }
+        // `*.yaml` fixtures have no way to request non-default parse/write
+        // options; see `FixtureConfig` for fixtures that can.
+        Test { name, input, kind, config: FixtureConfig::default() }
+    }
+}
+
+// Parse/write options a fixture can pin, taken either as this default (for
+// `*.yaml` fixtures) or from a `<name>.poscar` fixture's leading comment
+// block; see `split_fixture_config`.
+#[derive(Debug, Clone, Default)]
+struct FixtureConfig {
+    precision: Option<usize>,
+    column_width: Option<usize>,
+    indent: Option<usize>,
+    symbols: Option<bool>,
+    coord_system: Option<CoordSystem>,
+    lints: LintLevels,
+}
+
+impl FixtureConfig {
+    fn writer(&self) -> WriterBuilder {
+        let mut builder = WriterBuilder::new();
+        if let Some(precision) = self.precision { builder.precision(precision); }
+        if let Some(width) = self.column_width { builder.column_width(width); }
+        if let Some(indent) = self.indent { builder.indent(indent); }
+        if let Some(symbols) = self.symbols { builder.symbols(symbols); }
+        if let Some(system) = self.coord_system { builder.coord_system(system); }
+        builder
+    }
+}
+
+// Strips a leading block of `# key: value` comment lines off of a `.poscar`
+// fixture's text (POSCAR itself has no comment syntax, so this is safe: a
+// real POSCAR's title line essentially never starts with `#`), parsing each
+// into a `FixtureConfig` override. Returns the config and the remaining text,
+// which is what actually gets handed to the parser.
+fn split_fixture_config(text: &str) -> (FixtureConfig, String) {
+    let mut config = FixtureConfig::default();
+    let mut consumed = 0;
+    for line in text.lines() {
+        if !line.trim_start().starts_with('#') { break; }
+        consumed += line.len() + 1;
+
+        let body = line.trim_start()[1..].trim();
+        if let Some(colon) = body.find(':') {
+            let key = body[..colon].trim();
+            let value = body[colon + 1..].trim();
+            apply_fixture_config(&mut config, key, value);
+        }
     }
+    (config, text[consumed.min(text.len())..].to_string())
+}
+
+fn apply_fixture_config(config: &mut FixtureConfig, key: &str, value: &str) {
+    match key {
+        "precision" => config.precision = value.parse().ok(),
+        "column_width" => config.column_width = value.parse().ok(),
+        "indent" => config.indent = value.parse().ok(),
+        "symbols" => config.symbols = value.parse().ok(),
+        "coord_system" => config.coord_system = match value {
+            "cartesian" => Some(CoordSystem::Cartesian),
+            "direct" => Some(CoordSystem::Direct),
+            _ => None,
+        },
+        // e.g. `# lint: ambiguous-scale-axes=error`
+        "lint" => if let Some(eq) = value.find('=') {
+            let kind = parse_diagnostic_kind(value[..eq].trim());
+            let level = parse_level(value[eq + 1..].trim());
+            if let (Some(kind), Some(level)) = (kind, level) {
+                config.lints.set(kind, level);
+            }
+        },
+        _ => {},
+    }
+}
+
+fn parse_diagnostic_kind(s: &str) -> Option<DiagnosticKind> {
+    Some(match s {
+        "indented-coord-line" => DiagnosticKind::IndentedCoordLine,
+        "suspiciously-direct" => DiagnosticKind::SuspiciouslyDirect,
+        "ambiguous-scale-axes" => DiagnosticKind::AmbiguousScaleAxes,
+        "trailing-count-tokens-ignored" => DiagnosticKind::TrailingCountTokensIgnored,
+        _ => return None,
+    })
 }
 
+fn parse_level(s: &str) -> Option<Level> {
+    match s {
+        "warning" => Some(Level::Warning),
+        "error" => Some(Level::Error),
+        _ => None,
+    }
+}
+
+// `.err` files, being ordinary text files, carry the POSIX-mandated trailing
+// newline that an error message doesn't; strip it so the substring search
+// looks for exactly the message. (`.expected` files are compared verbatim,
+// since writer output itself ends in a newline.)
+fn strip_final_newline(mut s: String) -> String {
+    if s.ends_with('\n') { s.pop(); }
+    s
+}
+
+// Overwrites a fixture's `output`/`error` field with a freshly produced
+// string, for BLESS mode. Leaves `name` and `input` untouched.
+//
+// `actual_location`, when given, also heals a `Failure` fixture's `line`/
+// `col` to match what the parser actually reported — otherwise a fixture
+// whose expected error site has simply drifted could never be blessed, since
+// `error`'s text already matches and there would be nothing left to rewrite.
+// Only takes effect when the fixture already opted into a location
+// assertion (`line` was `Some`); BLESS never adds one that wasn't there.
+fn apply_bless(raw: &mut RawTest, bonafide: String, actual_location: Option<::poscar::ErrorLocation>) {
+    match raw {
+        RawTest::Success { input, output, .. } => *output = input.reshape_like(bonafide),
+        RawTest::Failure { input, error, line, col, .. } => {
+            *error = input.reshape_like(bonafide);
+            if let (Some(_), Some(actual)) = (line.as_ref(), actual_location) {
+                *line = Some(actual.line + 1);
+                if col.is_some() {
+                    *col = actual.col.map(|c| c + 1);
+                }
+            }
+        },
+    }
+}
 
 fn collect_tests(dir: &Path) -> Result<Vec<TestSpec>, FailError> {
     let mut out = vec![];
@@ -103,20 +314,50 @@ fn collect_tests(dir: &Path) -> Result<Vec<TestSpec>, FailError> {
         let path = entry.path();
         if path.extension() == Some("yaml".as_ref()) {
             let file = fs::File::open(path.as_path())?;
-            let cases: Vec<RawTest> = ::serde_yaml::from_reader(file)
+            let raw: Vec<RawTest> = ::serde_yaml::from_reader(file)
                                       .with_context(|_| {
                                           format!("error reading {}", path.as_path().display())
                                       })?;
-            let cases = cases.into_iter().map(RawTest::unraw).collect();
+            let cases = raw.iter().cloned().map(RawTest::unraw).collect();
 
             let basename = path.file_stem().unwrap().to_string_lossy().to_string();
-            out.push(TestSpec { basename, cases });
+            let kind = FixtureKind::Yaml { raw: Mutex::new(raw), blessed: AtomicBool::new(false) };
+            out.push(TestSpec { basename, path, kind, cases });
+        } else if path.extension() == Some("poscar".as_ref()) {
+            out.push(collect_external_fixture(&path)?);
         }
     }
     Ok(out)
 }
 
-struct Failure(String, Error);
+// Picks up a `<name>.poscar` source file, paired with either a
+// `<name>.expected` (success) or `<name>.err` (failure) target, as an
+// alternative to hand-escaping a large, real-world POSCAR into YAML.
+fn collect_external_fixture(path: &Path) -> Result<TestSpec, FailError> {
+    let source = fs::read_to_string(path)
+        .with_context(|_| format!("error reading {}", path.display()))?;
+    let (config, input) = split_fixture_config(&source);
+
+    let expected_path = path.with_extension("expected");
+    let err_path = path.with_extension("err");
+    let (target_path, kind) = if expected_path.is_file() {
+        let expected = fs::read_to_string(&expected_path)
+            .with_context(|_| format!("error reading {}", expected_path.display()))?;
+        (expected_path, TestKind::Success(expected))
+    } else if err_path.is_file() {
+        let expected = fs::read_to_string(&err_path)
+            .with_context(|_| format!("error reading {}", err_path.display()))?;
+        (err_path, TestKind::Failure(strip_final_newline(expected), None))
+    } else {
+        return Err(::poscar::failure::err_msg(format!(
+            "{}: no paired `.expected` or `.err` file", path.display(),
+        )));
+    };
+
+    let basename = path.file_stem().unwrap().to_string_lossy().to_string();
+    let case = Test { name: Some("parse".to_string()), input, kind, config };
+    Ok(TestSpec { basename, path: path.to_owned(), kind: FixtureKind::External { target_path }, cases: vec![case] })
+}
 
 #[derive(Debug)]
 enum Error {
@@ -133,17 +374,210 @@ enum Error {
     ErrorMismatch {
         bonafide: String,
         expected: String,
+        // Populated only when the fixture specified an expected error
+        // location and it didn't match what the parser actually reported.
+        location: Option<LocationMismatch>,
     },
 }
 
+// Enough context to render a caret-annotated "claimed vs actual" snippet for
+// an error site that didn't match a fixture's expected `line`/`col`.
+#[derive(Debug)]
+struct LocationMismatch {
+    expected: ExpectedLocation,
+    actual: Option<::poscar::ErrorLocation>,
+    // The actual reported line's text, if the parser reported one.
+    line_text: Option<String>,
+}
+
+impl Error {
+    // The freshly produced text, for the error kinds BLESS can fix by
+    // overwriting the fixture. `None` for kinds with nothing to write back
+    // (an outright parse failure, or a parse that unexpectedly succeeded).
+    fn bonafide(&self) -> Option<String> {
+        match *self {
+            Error::Mismatch { ref bonafide, .. } |
+            Error::ErrorMismatch { ref bonafide, .. } => Some(bonafide.clone()),
+            Error::Error(_) | Error::NoError => None,
+        }
+    }
+
+    // The location the parser actually reported, for a location-only
+    // `ErrorMismatch` (one where the message already matched). BLESS needs
+    // this to heal a fixture's `line`/`col` alongside its `error` text;
+    // `None` for every other kind, including a substring mismatch (there,
+    // `error` is being rewritten wholesale, so a stale `line`/`col` is the
+    // fixture author's to reconcile by hand).
+    fn actual_location(&self) -> Option<::poscar::ErrorLocation> {
+        match *self {
+            Error::ErrorMismatch { location: Some(ref location), .. } => location.actual,
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Error(ref e) => write!(f, "parse failed:\n{}", e),
+            Error::NoError => write!(f, "expected a parse error, but parsing succeeded"),
+            Error::Mismatch { ref bonafide, ref expected } => {
+                write!(f, "{}", unified_diff(expected, bonafide))
+            },
+            // When only the location is wrong, `bonafide` already contains
+            // `expected` as a substring, so a line diff between the two
+            // would just be noise; show the location mismatch on its own.
+            Error::ErrorMismatch { location: Some(ref location), .. } => {
+                write!(f, "{}", location)
+            },
+            Error::ErrorMismatch { ref bonafide, ref expected, location: None } => {
+                write!(f, "{}", unified_diff(expected, bonafide))
+            },
+        }
+    }
+}
+
+impl fmt::Display for LocationMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected error at line {}", self.expected.line)?;
+        if let Some(col) = self.expected.col {
+            write!(f, ", column {}", col)?;
+        }
+        write!(f, "\n")?;
+
+        match self.actual {
+            None => write!(f, "parser reported no location at all\n")?,
+            Some(actual) => {
+                write!(f, "parser instead reported line {}", actual.line + 1)?;
+                if let Some(col) = actual.col {
+                    write!(f, ", column {}", col + 1)?;
+                }
+                write!(f, "\n")?;
+
+                if let Some(ref line_text) = self.line_text {
+                    writeln!(f, "{}", line_text)?;
+                    if let Some(col) = actual.col {
+                        writeln!(f, "{}^", " ".repeat(col))?;
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+// How many unchanged lines of context to print around each run of changed
+// lines, in the style of `diff -u`.
+const DIFF_CONTEXT_SIZE: usize = 3;
+
+// `lcs_diff`'s DP table is `O(n*m)` in both time and memory. That's fine for
+// the hand-written fixtures this suite started with, but a real CONTCAR with
+// site velocities can run to tens of thousands of lines, where the full
+// table would be gigabytes. Past this many cells, `unified_diff` gives up on
+// a line-level diff and falls back to a one-line summary instead.
+const DIFF_MAX_CELLS: usize = 4_000_000;
+
+enum DiffOp<'a> { Same(&'a str), Removed(&'a str), Added(&'a str) }
+
+// Standard dynamic-programming LCS over the two line sequences, backtracked
+// into a sequence of Same/Removed/Added operations.
+fn lcs_diff<'a>(expected: &[&'a str], bonafide: &[&'a str]) -> Vec<DiffOp<'a>> {
+    let (n, m) = (expected.len(), bonafide.len());
+
+    let mut table = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            table[i][j] = if expected[i] == bonafide[j] {
+                table[i + 1][j + 1] + 1
+            } else {
+                table[i + 1][j].max(table[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if expected[i] == bonafide[j] {
+            ops.push(DiffOp::Same(expected[i]));
+            i += 1;
+            j += 1;
+        } else if table[i + 1][j] >= table[i][j + 1] {
+            ops.push(DiffOp::Removed(expected[i]));
+            i += 1;
+        } else {
+            ops.push(DiffOp::Added(bonafide[j]));
+            j += 1;
+        }
+    }
+    ops.extend(expected[i..].iter().map(|&s| DiffOp::Removed(s)));
+    ops.extend(bonafide[j..].iter().map(|&s| DiffOp::Added(s)));
+    ops
+}
+
+// Renders a contextual unified diff between `expected` and `bonafide`,
+// showing only hunks of changed lines plus `DIFF_CONTEXT_SIZE` lines of
+// unchanged context on either side, so a failing round-trip of a large
+// POSCAR reads like a readable patch instead of two dumped strings.
+fn unified_diff(expected: &str, bonafide: &str) -> String {
+    let expected_lines: Vec<&str> = expected.lines().collect();
+    let bonafide_lines: Vec<&str> = bonafide.lines().collect();
+
+    let cells = expected_lines.len().saturating_mul(bonafide_lines.len());
+    if cells > DIFF_MAX_CELLS {
+        return format!(
+            "expected {} lines, got {} lines (diff suppressed: {} line pairs exceeds the {}-cell limit on lcs_diff's table)",
+            expected_lines.len(), bonafide_lines.len(), cells, DIFF_MAX_CELLS,
+        );
+    }
+
+    let ops = lcs_diff(&expected_lines, &bonafide_lines);
+
+    let changed: Vec<usize> = ops.iter().enumerate()
+        .filter(|&(_, op)| !matches!(*op, DiffOp::Same(_)))
+        .map(|(i, _)| i)
+        .collect();
+
+    if changed.is_empty() {
+        return "(no line-level difference; check trailing whitespace)".to_string();
+    }
+
+    // Merge changed lines into hunks, growing each one by `DIFF_CONTEXT_SIZE`
+    // lines of context and folding in any hunk whose context would overlap.
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for idx in changed {
+        let lo = idx.saturating_sub(DIFF_CONTEXT_SIZE);
+        let hi = (idx + DIFF_CONTEXT_SIZE).min(ops.len() - 1);
+        match hunks.last_mut() {
+            Some(&mut (_, ref mut last_hi)) if lo <= *last_hi + 1 => *last_hi = hi,
+            _ => hunks.push((lo, hi)),
+        }
+    }
+
+    let mut out = String::new();
+    for (n, &(lo, hi)) in hunks.iter().enumerate() {
+        if n > 0 {
+            out.push_str("@@\n");
+        }
+        for op in &ops[lo..=hi] {
+            match *op {
+                DiffOp::Same(line) => out.push_str(&format!(" {}\n", line)),
+                DiffOp::Removed(line) => out.push_str(&format!("-{}\n", line)),
+                DiffOp::Added(line) => out.push_str(&format!("+{}\n", line)),
+            }
+        }
+    }
+    out
+}
+
 impl Test {
     fn run(&self) -> Result<(), Error> {
-        let Test { ref input, ref kind, .. } = *self;
+        let Test { ref input, ref kind, ref config, .. } = *self;
         match *kind {
             TestKind::Success(ref expected) => {
-                match ::poscar::from_reader(input.as_bytes()) {
+                match ::poscar::Poscar::from_reader_with_warnings(input.as_bytes(), &config.lints) {
                     Err(e) => { return Err(Error::Error(e)); },
-                    Ok(poscar) => {
+                    Ok((poscar, _diagnostics)) => {
                         // We serialize back into text before comparing against the expected.
                         // This has the advantage that a parser bug cannot inadvertently
                         //   affect 'bonafide' and 'expected' in the same way.
@@ -153,7 +587,7 @@ impl Test {
                         // I suspect that an automatic outfile-generating script and careful
                         // review of git diffs should be good enough to work around that disadvantage.
                         let mut bonafide = vec![];
-                        ::poscar::to_writer(&mut bonafide, &poscar).unwrap();
+                        config.writer().write(&mut bonafide, &poscar).unwrap();
                         let bonafide = String::from_utf8(bonafide).unwrap();
 
                         let expected = expected.clone();
@@ -163,15 +597,31 @@ impl Test {
                     },
                 }
             },
-            TestKind::Failure(ref expected) => {
-                match ::poscar::from_reader(input.as_bytes()) {
+            TestKind::Failure(ref expected, ref at) => {
+                match ::poscar::Poscar::from_reader_with_warnings(input.as_bytes(), &config.lints) {
                     Ok(_) => { return Err(Error::NoError); },
                     Err(e) => {
                         // do a substring search
                         let bonafide = format!("{}", e);
                         let expected = expected.clone();
                         if !bonafide.contains(&expected[..]) {
-                            return Err(Error::ErrorMismatch { bonafide, expected })
+                            return Err(Error::ErrorMismatch { bonafide, expected, location: None });
+                        }
+
+                        if let Some(expected_at) = *at {
+                            let actual = ::poscar::Poscar::error_location(&e);
+                            let matches = actual.map_or(false, |a| {
+                                a.line + 1 == expected_at.line
+                                    && expected_at.col.map_or(true, |col| a.col.map(|c| c + 1) == Some(col))
+                            });
+                            if !matches {
+                                let line_text = actual
+                                    .map(|a| input.lines().nth(a.line).unwrap_or("").to_string());
+                                return Err(Error::ErrorMismatch {
+                                    bonafide, expected,
+                                    location: Some(LocationMismatch { expected: expected_at, actual, line_text }),
+                                });
+                            }
                         }
                     }
                 }